@@ -0,0 +1,473 @@
+//! Pluggable OS backend for [`GutexGroup`].
+//!
+//! The group only needs three things from the host: a way to identify the calling thread, a way to
+//! block ("wait on an address") and a way to wake blocked threads. Everything else is pure atomics.
+//! Factoring those out behind [`GutexBackend`] lets the same crate run on top of `std`/futex on a
+//! hosted OS or on top of a custom scheduler in a `#![no_std]` kernel, without forking the code.
+//!
+//! [`GutexGroup`]: crate::GutexGroup
+use core::time::Duration;
+
+/// Host integration required by [`GutexGroup`](crate::GutexGroup).
+///
+/// An implementation supplies the owning-word atomic, the thread identity stored in it and the
+/// block/wake primitives operating on that word. The value returned by [`Self::unlocked`] is
+/// reserved as the "unlocked" sentinel, so a real thread id must never equal it.
+pub trait GutexBackend {
+    /// The thread identity stored in the owning word.
+    type ThreadId: Copy + PartialEq;
+
+    /// The atomic word holding the owning thread id.
+    type Atomic: core::fmt::Debug;
+
+    /// Create the owning word with an initial value.
+    fn new_atomic(value: Self::ThreadId) -> Self::Atomic;
+
+    /// The "unlocked" sentinel. No live thread may ever report this from [`Self::current_thread`].
+    fn unlocked() -> Self::ThreadId;
+
+    /// Identity of the calling thread.
+    fn current_thread() -> Self::ThreadId;
+
+    /// Relaxed load of the owning word.
+    fn load(atomic: &Self::Atomic) -> Self::ThreadId;
+
+    /// `Acquire`/`Relaxed` compare-exchange used to claim the lock.
+    fn compare_exchange_acquire(
+        atomic: &Self::Atomic,
+        current: Self::ThreadId,
+        new: Self::ThreadId,
+    ) -> Result<Self::ThreadId, Self::ThreadId>;
+
+    /// `Release` store used to drop the lock.
+    fn store_release(atomic: &Self::Atomic, value: Self::ThreadId);
+
+    /// Mutate the word to a value different from its current one. Used by the condition variable to
+    /// advance its sequence counter.
+    fn bump(atomic: &Self::Atomic);
+
+    /// Raw pointer to the word, for the block/wake primitives.
+    fn as_ptr(atomic: &Self::Atomic) -> *mut Self::ThreadId;
+
+    /// Block until the word at `addr` changes away from `expected` or `timeout` elapses.
+    ///
+    /// Returns `false` if the wait was terminated by the timeout. `None` waits forever.
+    ///
+    /// # Safety
+    /// `addr` must point to a valid owning word produced by this backend.
+    unsafe fn wait(
+        addr: *mut Self::ThreadId,
+        expected: Self::ThreadId,
+        timeout: Option<Duration>,
+    ) -> bool;
+
+    /// Wake a single thread blocked in [`Self::wait`] on `addr`.
+    ///
+    /// # Safety
+    /// `addr` must point to a valid owning word produced by this backend.
+    unsafe fn wake_one(addr: *mut Self::ThreadId);
+
+    /// Wake every thread blocked in [`Self::wait`] on `addr`.
+    ///
+    /// # Safety
+    /// `addr` must point to a valid owning word produced by this backend.
+    unsafe fn wake_all(addr: *mut Self::ThreadId);
+
+    /// Whether this backend implements kernel priority inheritance.
+    ///
+    /// When `false`, [`GutexGroup::new_with_priority_inheritance`] silently behaves like
+    /// [`GutexGroup::new`] and none of the `*_pi` methods below are called.
+    ///
+    /// [`GutexGroup::new_with_priority_inheritance`]: crate::GutexGroup::new_with_priority_inheritance
+    /// [`GutexGroup::new`]: crate::GutexGroup::new
+    fn supports_priority_inheritance() -> bool {
+        false
+    }
+
+    /// Extract the owning thread id from a raw owning word, stripping any contended bit that the
+    /// priority-inheritance protocol may have set.
+    ///
+    /// The default is the identity, which is correct for the plain compare-exchange protocol where
+    /// the word holds exactly the thread id.
+    fn owner_of(raw: Self::ThreadId) -> Self::ThreadId {
+        raw
+    }
+
+    /// Acquire the lock using a priority-inheriting primitive, blocking until owned.
+    ///
+    /// The default is a no-op: a backend whose [`Self::supports_priority_inheritance`] returns
+    /// `false` never has this called (the group falls back to the compare-exchange path), so the
+    /// only way to reach the default body is a backend that opts in without overriding the `*_pi`
+    /// methods — a programming error caught by the `debug_assert!`.
+    ///
+    /// # Safety
+    /// `addr` must point to a valid owning word produced by this backend and the caller must not
+    /// already own it.
+    unsafe fn lock_pi(addr: *mut Self::ThreadId) {
+        let _ = addr;
+        debug_assert!(false, "backend does not support priority inheritance");
+    }
+
+    /// Try to acquire the lock using a priority-inheriting primitive without blocking.
+    ///
+    /// The default returns `false`; see [`Self::lock_pi`] for why it is never reached in practice.
+    ///
+    /// # Safety
+    /// See [`Self::lock_pi`].
+    unsafe fn try_lock_pi(addr: *mut Self::ThreadId) -> bool {
+        let _ = addr;
+        debug_assert!(false, "backend does not support priority inheritance");
+        false
+    }
+
+    /// Acquire the lock using a priority-inheriting primitive, giving up after `timeout`.
+    ///
+    /// The default returns `false`; see [`Self::lock_pi`] for why it is never reached in practice.
+    ///
+    /// # Safety
+    /// See [`Self::lock_pi`].
+    unsafe fn lock_pi_timeout(addr: *mut Self::ThreadId, timeout: Duration) -> bool {
+        let _ = (addr, timeout);
+        debug_assert!(false, "backend does not support priority inheritance");
+        false
+    }
+
+    /// Release a lock acquired with one of the `*_pi` methods.
+    ///
+    /// The default is a no-op; see [`Self::lock_pi`] for why it is never reached in practice.
+    ///
+    /// # Safety
+    /// `addr` must point to a word currently owned by the calling thread via the PI protocol.
+    unsafe fn unlock_pi(addr: *mut Self::ThreadId) {
+        let _ = addr;
+        debug_assert!(false, "backend does not support priority inheritance");
+    }
+}
+
+/// Default [`GutexBackend`] built on the host OS futex (Linux), `__ulock` (macOS) or
+/// `WaitOnAddress` (Windows). Only available with the `std` feature.
+#[derive(Debug)]
+pub struct Futex;
+
+#[cfg(feature = "std")]
+mod futex {
+    use super::{Futex, GutexBackend};
+    use core::sync::atomic::Ordering;
+    use core::time::Duration;
+    use std::io::Error;
+
+    #[cfg(target_os = "linux")]
+    type Tid = i32;
+    #[cfg(target_os = "macos")]
+    type Tid = u64;
+    #[cfg(target_os = "windows")]
+    type Tid = u32;
+
+    #[cfg(target_os = "linux")]
+    type Atomic = core::sync::atomic::AtomicI32;
+    #[cfg(target_os = "macos")]
+    type Atomic = core::sync::atomic::AtomicU64;
+    #[cfg(target_os = "windows")]
+    type Atomic = core::sync::atomic::AtomicU32;
+
+    impl GutexBackend for Futex {
+        type ThreadId = Tid;
+        type Atomic = Atomic;
+
+        fn new_atomic(value: Tid) -> Atomic {
+            Atomic::new(value)
+        }
+
+        fn unlocked() -> Tid {
+            0
+        }
+
+        #[cfg(target_os = "linux")]
+        fn current_thread() -> Tid {
+            unsafe { libc::gettid() }
+        }
+
+        #[cfg(target_os = "macos")]
+        fn current_thread() -> Tid {
+            let mut id = 0;
+            assert_eq!(unsafe { libc::pthread_threadid_np(0, &mut id) }, 0);
+            id
+        }
+
+        #[cfg(target_os = "windows")]
+        fn current_thread() -> Tid {
+            unsafe { windows_sys::Win32::System::Threading::GetCurrentThreadId() }
+        }
+
+        fn load(atomic: &Atomic) -> Tid {
+            atomic.load(Ordering::Relaxed)
+        }
+
+        fn compare_exchange_acquire(atomic: &Atomic, current: Tid, new: Tid) -> Result<Tid, Tid> {
+            atomic.compare_exchange(current, new, Ordering::Acquire, Ordering::Relaxed)
+        }
+
+        fn store_release(atomic: &Atomic, value: Tid) {
+            atomic.store(value, Ordering::Release);
+        }
+
+        fn bump(atomic: &Atomic) {
+            atomic.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn as_ptr(atomic: &Atomic) -> *mut Tid {
+            atomic.as_ptr()
+        }
+
+        #[cfg(target_os = "linux")]
+        unsafe fn wait(addr: *mut Tid, expected: Tid, timeout: Option<Duration>) -> bool {
+            use libc::{
+                syscall, timespec, ETIMEDOUT, EAGAIN, FUTEX_PRIVATE_FLAG, FUTEX_WAIT, SYS_futex,
+            };
+
+            // Build a relative timespec. A null pointer makes FUTEX_WAIT block forever.
+            let ts = timeout.map(|d| timespec {
+                tv_sec: d.as_secs() as _,
+                tv_nsec: d.subsec_nanos() as _,
+            });
+            let ts_ptr = ts
+                .as_ref()
+                .map(|t| t as *const timespec)
+                .unwrap_or(core::ptr::null());
+
+            if unsafe {
+                syscall(SYS_futex, addr, FUTEX_WAIT | FUTEX_PRIVATE_FLAG, expected, ts_ptr)
+            } < 0
+            {
+                let e = Error::last_os_error();
+
+                match e.raw_os_error().unwrap() {
+                    EAGAIN => {}
+                    ETIMEDOUT => return false,
+                    _ => panic!("FUTEX_WAIT failed: {e}"),
+                }
+            }
+
+            true
+        }
+
+        #[cfg(target_os = "macos")]
+        unsafe fn wait(addr: *mut Tid, expected: Tid, timeout: Option<Duration>) -> bool {
+            use libc::ETIMEDOUT;
+            use ulock_sys::__ulock_wait;
+            use ulock_sys::darwin19::UL_COMPARE_AND_WAIT64;
+
+            // __ulock_wait takes a u32 microsecond timeout where 0 means wait forever.
+            let us = match timeout {
+                Some(d) => d.as_micros().clamp(1, u32::MAX as u128) as u32,
+                None => 0,
+            };
+
+            if __ulock_wait(UL_COMPARE_AND_WAIT64, addr.cast(), expected, us) != 0 {
+                let e = Error::last_os_error();
+
+                if e.raw_os_error().unwrap() == ETIMEDOUT {
+                    return false;
+                }
+
+                panic!("__ulock_wait() failed: {e}");
+            }
+
+            true
+        }
+
+        #[cfg(target_os = "windows")]
+        unsafe fn wait(addr: *mut Tid, expected: Tid, timeout: Option<Duration>) -> bool {
+            use windows_sys::Win32::Foundation::{GetLastError, ERROR_TIMEOUT};
+            use windows_sys::Win32::System::Threading::{WaitOnAddress, INFINITE};
+
+            // WaitOnAddress takes a millisecond timeout where INFINITE means wait forever.
+            let ms = match timeout {
+                Some(d) => d.as_millis().clamp(1, (INFINITE - 1) as u128) as u32,
+                None => INFINITE,
+            };
+
+            if unsafe { WaitOnAddress(addr.cast(), &expected as *const Tid as _, 4, ms) } == 0 {
+                if unsafe { GetLastError() } == ERROR_TIMEOUT {
+                    return false;
+                }
+
+                panic!("WaitOnAddress() failed: {}", Error::last_os_error());
+            }
+
+            true
+        }
+
+        #[cfg(target_os = "linux")]
+        unsafe fn wake_one(addr: *mut Tid) {
+            use libc::{syscall, SYS_futex, FUTEX_PRIVATE_FLAG, FUTEX_WAKE};
+
+            if unsafe { syscall(SYS_futex, addr, FUTEX_WAKE | FUTEX_PRIVATE_FLAG, 1) } < 0 {
+                panic!("FUTEX_WAKE failed: {}", Error::last_os_error());
+            }
+        }
+
+        #[cfg(target_os = "macos")]
+        unsafe fn wake_one(addr: *mut Tid) {
+            use libc::ENOENT;
+            use ulock_sys::__ulock_wake;
+            use ulock_sys::darwin19::UL_COMPARE_AND_WAIT64;
+
+            if __ulock_wake(UL_COMPARE_AND_WAIT64, addr.cast(), 0) != 0 {
+                // __ulock_wake will return ENOENT if no other threads being waiting on the address.
+                let e = Error::last_os_error();
+
+                if e.raw_os_error().unwrap() != ENOENT {
+                    panic!("__ulock_wake() failed: {e}");
+                }
+            }
+        }
+
+        #[cfg(target_os = "windows")]
+        unsafe fn wake_one(addr: *mut Tid) {
+            use windows_sys::Win32::System::Threading::WakeByAddressSingle;
+
+            unsafe { WakeByAddressSingle(addr.cast()) };
+        }
+
+        #[cfg(target_os = "linux")]
+        unsafe fn wake_all(addr: *mut Tid) {
+            use libc::{syscall, SYS_futex, FUTEX_PRIVATE_FLAG, FUTEX_WAKE};
+
+            if unsafe { syscall(SYS_futex, addr, FUTEX_WAKE | FUTEX_PRIVATE_FLAG, i32::MAX) } < 0 {
+                panic!("FUTEX_WAKE failed: {}", Error::last_os_error());
+            }
+        }
+
+        #[cfg(target_os = "macos")]
+        unsafe fn wake_all(addr: *mut Tid) {
+            use libc::ENOENT;
+            use ulock_sys::__ulock_wake;
+            use ulock_sys::darwin19::{UL_COMPARE_AND_WAIT64, ULF_WAKE_ALL};
+
+            if __ulock_wake(UL_COMPARE_AND_WAIT64 | ULF_WAKE_ALL, addr.cast(), 0) != 0 {
+                // __ulock_wake will return ENOENT if no other threads being waiting on the address.
+                let e = Error::last_os_error();
+
+                if e.raw_os_error().unwrap() != ENOENT {
+                    panic!("__ulock_wake() failed: {e}");
+                }
+            }
+        }
+
+        #[cfg(target_os = "windows")]
+        unsafe fn wake_all(addr: *mut Tid) {
+            use windows_sys::Win32::System::Threading::WakeByAddressAll;
+
+            unsafe { WakeByAddressAll(addr.cast()) };
+        }
+
+        // Only Linux exposes kernel PI futexes; the other hosts fall back to the plain protocol.
+        #[cfg(target_os = "linux")]
+        fn supports_priority_inheritance() -> bool {
+            true
+        }
+
+        #[cfg(target_os = "linux")]
+        fn owner_of(raw: Tid) -> Tid {
+            // Strip the FUTEX_WAITERS / FUTEX_OWNER_DIED bits the kernel maintains, leaving the TID.
+            raw & 0x3fffffff
+        }
+
+        #[cfg(target_os = "linux")]
+        unsafe fn lock_pi(addr: *mut Tid) {
+            use libc::{syscall, EINTR, FUTEX_LOCK_PI, FUTEX_PRIVATE_FLAG, SYS_futex};
+
+            // Retry on EINTR; any other error means the futex state is corrupt.
+            loop {
+                if unsafe {
+                    syscall(SYS_futex, addr, FUTEX_LOCK_PI | FUTEX_PRIVATE_FLAG, 0, core::ptr::null::<libc::timespec>())
+                } == 0
+                {
+                    return;
+                }
+
+                let e = Error::last_os_error();
+
+                if e.raw_os_error().unwrap() != EINTR {
+                    panic!("FUTEX_LOCK_PI failed: {e}");
+                }
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        unsafe fn try_lock_pi(addr: *mut Tid) -> bool {
+            use libc::{syscall, EAGAIN, FUTEX_PRIVATE_FLAG, FUTEX_TRYLOCK_PI, SYS_futex};
+
+            if unsafe { syscall(SYS_futex, addr, FUTEX_TRYLOCK_PI | FUTEX_PRIVATE_FLAG) } == 0 {
+                return true;
+            }
+
+            let e = Error::last_os_error();
+
+            if e.raw_os_error().unwrap() != EAGAIN {
+                panic!("FUTEX_TRYLOCK_PI failed: {e}");
+            }
+
+            false
+        }
+
+        #[cfg(target_os = "linux")]
+        unsafe fn lock_pi_timeout(addr: *mut Tid, timeout: Duration) -> bool {
+            use libc::{
+                clock_gettime, syscall, timespec, ETIMEDOUT, CLOCK_REALTIME, FUTEX_LOCK_PI,
+                FUTEX_PRIVATE_FLAG, SYS_futex,
+            };
+
+            // FUTEX_LOCK_PI uses an *absolute* CLOCK_REALTIME deadline, unlike FUTEX_WAIT.
+            let mut now = timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+
+            assert_eq!(unsafe { clock_gettime(CLOCK_REALTIME, &mut now) }, 0);
+
+            let mut nsec = now.tv_nsec as i128 + timeout.subsec_nanos() as i128;
+            let mut sec = now.tv_sec as i128 + timeout.as_secs() as i128;
+            sec += nsec / 1_000_000_000;
+            nsec %= 1_000_000_000;
+
+            let deadline = timespec {
+                tv_sec: sec as _,
+                tv_nsec: nsec as _,
+            };
+
+            loop {
+                if unsafe {
+                    syscall(
+                        SYS_futex,
+                        addr,
+                        FUTEX_LOCK_PI | FUTEX_PRIVATE_FLAG,
+                        0,
+                        &deadline as *const timespec,
+                    )
+                } == 0
+                {
+                    return true;
+                }
+
+                let e = Error::last_os_error();
+
+                match e.raw_os_error().unwrap() {
+                    libc::EINTR => continue,
+                    ETIMEDOUT => return false,
+                    _ => panic!("FUTEX_LOCK_PI failed: {e}"),
+                }
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        unsafe fn unlock_pi(addr: *mut Tid) {
+            use libc::{syscall, FUTEX_PRIVATE_FLAG, FUTEX_UNLOCK_PI, SYS_futex};
+
+            if unsafe { syscall(SYS_futex, addr, FUTEX_UNLOCK_PI | FUTEX_PRIVATE_FLAG) } < 0 {
+                panic!("FUTEX_UNLOCK_PI failed: {}", Error::last_os_error());
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::backend::{Futex, GutexBackend};
+use crate::{GutexWriteGuard, LockResult, PoisonError};
+
+/// A condition variable that cooperates with a [`GutexGroup`].
+///
+/// This is the [`Gutex`] equivalent of [`std::sync::Condvar`]. It is built directly on the futex
+/// primitives backing the group so a thread can block while holding a [`GutexWriteGuard`] and be
+/// woken once another thread updates the protected state.
+///
+/// [`Gutex`]: crate::Gutex
+/// [`GutexGroup`]: crate::GutexGroup
+#[derive(Debug)]
+pub struct GutexCondvar<B: GutexBackend = Futex> {
+    // A counter that we futex-wait on. Every notify mutates it so a wait that races with a notify
+    // observes the change and returns instead of sleeping.
+    seq: B::Atomic,
+}
+
+#[cfg(feature = "std")]
+impl GutexCondvar<Futex> {
+    /// Create a new condition variable backed by the default futex [`Futex`] backend.
+    pub fn new() -> Self {
+        Self::new_in()
+    }
+}
+
+impl<B: GutexBackend> GutexCondvar<B> {
+    /// Create a new condition variable backed by `B`.
+    pub fn new_in() -> Self {
+        Self {
+            seq: B::new_atomic(B::unlocked()),
+        }
+    }
+
+    /// Block the current thread until this condition variable receives a notification.
+    ///
+    /// The group lock held by `guard` is released while waiting and re-acquired before returning,
+    /// mirroring [`std::sync::Condvar::wait`]. If the calling thread holds other guards on the same
+    /// group, the group stays locked while waiting.
+    ///
+    /// # Errors
+    /// If a thread panicked while holding a write access on any member of the group, the
+    /// re-acquired guard is wrapped in an [`Err`] so the possibly-inconsistent state is not used by
+    /// accident, exactly like [`Gutex::write`](crate::Gutex::write).
+    pub fn wait<'a, T>(
+        &self,
+        guard: GutexWriteGuard<'a, T, B>,
+    ) -> LockResult<GutexWriteGuard<'a, T, B>> {
+        // Snapshot the counter while we still hold the group lock so a notify that happens after
+        // we release the group is guaranteed to have changed the counter, avoiding a lost wakeup.
+        let seq = B::load(&self.seq);
+        let group = guard.group();
+        let active = guard.active_ptr();
+        let value = guard.value_ptr();
+
+        // Release the group so other threads can make progress. Dropping the guard resets active to
+        // 0 and releases the underlying group lock unless this thread holds other guards on it.
+        drop(guard);
+
+        // Sleep until notified. A spurious wakeup or a racing notify simply returns early, which is
+        // allowed because the caller must re-check the condition.
+        unsafe { B::wait(B::as_ptr(&self.seq), seq, None) };
+
+        // Re-acquire the group and re-establish write access.
+        let lock = group.lock();
+
+        // SAFETY: We own the group again so active and value are exclusively ours.
+        let guard = unsafe {
+            *active = usize::MAX;
+            GutexWriteGuard::new(lock, active, value)
+        };
+
+        if group.is_poisoned() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    /// Wake up one thread blocked on this condition variable.
+    ///
+    /// This should be called while holding the group lock so the counter is mutated before any
+    /// waiter observes it.
+    pub fn notify_one(&self) {
+        B::bump(&self.seq);
+
+        unsafe { B::wake_one(B::as_ptr(&self.seq)) };
+    }
+
+    /// Wake up all threads blocked on this condition variable.
+    ///
+    /// This should be called while holding the group lock so the counter is mutated before any
+    /// waiter observes it.
+    pub fn notify_all(&self) {
+        B::bump(&self.seq);
+
+        unsafe { B::wake_all(B::as_ptr(&self.seq)) };
+    }
+}
+
+impl<B: GutexBackend> Default for GutexCondvar<B> {
+    fn default() -> Self {
+        Self::new_in()
+    }
+}
@@ -1,31 +1,86 @@
+use crate::backend::{Futex, GutexBackend};
 use crate::Gutex;
-use std::cell::UnsafeCell;
-use std::io::Error;
-use std::marker::PhantomData;
-use std::rc::Rc;
-use std::sync::atomic::Ordering;
-use std::sync::Arc;
+use alloc::sync::Arc;
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
 
 /// Group of [`Gutex`].
 #[derive(Debug)]
-pub struct GutexGroup {
-    owning: ThreadId,
+pub struct GutexGroup<B: GutexBackend = Futex> {
+    owning: B::Atomic,
     active: UnsafeCell<usize>,
+    poisoned: AtomicBool,
+    priority_inheritance: bool,
 }
 
-impl GutexGroup {
-    /// Create a new group.
+#[cfg(feature = "std")]
+impl GutexGroup<Futex> {
+    /// Create a new group backed by the default futex [`Futex`] backend.
     ///
     /// All members spawn within the same group will share a single mutex.
     pub fn new() -> Arc<Self> {
+        Self::new_in()
+    }
+
+    /// Create a new group that uses priority inheritance to avoid priority inversion.
+    ///
+    /// On backends that support it (Linux, via `FUTEX_LOCK_PI`), the kernel boosts the thread
+    /// currently holding the group to the priority of the highest-priority waiter for the duration
+    /// of the wait. On backends without PI futexes this is identical to [`Self::new`].
+    pub fn new_with_priority_inheritance() -> Arc<Self> {
+        Self::new_with_priority_inheritance_in()
+    }
+}
+
+impl<B: GutexBackend> GutexGroup<B> {
+    /// Create a new group backed by `B`.
+    ///
+    /// All members spawn within the same group will share a single mutex.
+    pub fn new_in() -> Arc<Self> {
         Arc::new(Self {
-            owning: ThreadId::new(0),
+            owning: B::new_atomic(B::unlocked()),
             active: UnsafeCell::new(0),
+            poisoned: AtomicBool::new(false),
+            priority_inheritance: false,
         })
     }
 
+    /// Create a new group backed by `B` that uses priority inheritance to avoid priority inversion.
+    ///
+    /// See [`Self::new_with_priority_inheritance`] for the semantics.
+    pub fn new_with_priority_inheritance_in() -> Arc<Self> {
+        Arc::new(Self {
+            owning: B::new_atomic(B::unlocked()),
+            active: UnsafeCell::new(0),
+            poisoned: AtomicBool::new(false),
+            priority_inheritance: true,
+        })
+    }
+
+    /// Whether this group actually uses the priority-inheritance protocol.
+    fn uses_pi(&self) -> bool {
+        self.priority_inheritance && B::supports_priority_inheritance()
+    }
+
+    /// Returns `true` if a thread panicked while holding a write guard on any member of this group.
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+
+    /// Mark this group as poisoned.
+    pub(crate) fn poison(&self) {
+        self.poisoned.store(true, Ordering::Relaxed);
+    }
+
+    /// Clear the poisoned state of this group.
+    pub(crate) fn clear_poison(&self) {
+        self.poisoned.store(false, Ordering::Relaxed);
+    }
+
     /// Spawn a new member for this group.
-    pub fn spawn<T>(self: &Arc<Self>, value: T) -> Gutex<T> {
+    pub fn spawn<T>(self: &Arc<Self>, value: T) -> Gutex<T, B> {
         Gutex {
             group: self.clone(),
             active: UnsafeCell::new(0),
@@ -34,127 +89,162 @@ impl GutexGroup {
     }
 
     #[inline(never)]
-    pub(crate) fn lock(&self) -> GroupGuard {
-        // Check if the calling thread already own the lock.
-        let current = Self::current_thread();
+    pub(crate) fn lock(&self) -> GroupGuard<'_, B> {
+        // Check if the calling thread already own the lock. This must happen before touching the
+        // futex because PI futexes are not recursive.
+        let current = B::current_thread();
 
-        if current == self.owning.load(Ordering::Relaxed) {
+        if current == B::owner_of(B::load(&self.owning)) {
             // SAFETY: This is safe because the current thread own the lock.
             return unsafe { GroupGuard::new(self) };
         }
 
-        // Acquire the lock.
-        while let Err(owning) =
-            self.owning
-                .compare_exchange(0, current, Ordering::Acquire, Ordering::Relaxed)
-        {
-            // Wait for the lock to unlock.
-            unsafe { Self::wait_unlock(self.owning.as_ptr(), owning) };
+        if self.uses_pi() {
+            // The kernel sets the owning word to our TID (plus the contended bit) on success.
+            unsafe { B::lock_pi(B::as_ptr(&self.owning)) };
+        } else {
+            // Acquire the lock.
+            while let Err(owning) =
+                B::compare_exchange_acquire(&self.owning, B::unlocked(), current)
+            {
+                // Wait for the lock to unlock.
+                unsafe { B::wait(B::as_ptr(&self.owning), owning, None) };
+            }
         }
 
-        // SAFETY: This is safe because the current thread acquire the lock successfully by the
-        // above compare_exchange().
+        // SAFETY: This is safe because the current thread acquire the lock successfully above.
         unsafe { GroupGuard::new(self) }
     }
 
-    #[cfg(target_os = "linux")]
-    fn current_thread() -> i32 {
-        unsafe { libc::gettid() }
-    }
-
-    #[cfg(target_os = "macos")]
-    fn current_thread() -> u64 {
-        let mut id = 0;
-        assert_eq!(unsafe { libc::pthread_threadid_np(0, &mut id) }, 0);
-        id
-    }
-
-    #[cfg(target_os = "windows")]
-    fn current_thread() -> u32 {
-        unsafe { windows_sys::Win32::System::Threading::GetCurrentThreadId() }
-    }
+    /// Try to acquire the lock without blocking.
+    ///
+    /// Returns [`None`] immediately if the group is currently owned by another thread instead of
+    /// waiting on the futex.
+    pub(crate) fn try_lock(&self) -> Option<GroupGuard<'_, B>> {
+        // Check if the calling thread already own the lock.
+        let current = B::current_thread();
 
-    #[cfg(target_os = "linux")]
-    unsafe fn wait_unlock(addr: *mut i32, owning: i32) {
-        use libc::{syscall, SYS_futex, EAGAIN, FUTEX_PRIVATE_FLAG, FUTEX_WAIT};
+        if current == B::owner_of(B::load(&self.owning)) {
+            // SAFETY: This is safe because the current thread own the lock.
+            return Some(unsafe { GroupGuard::new(self) });
+        }
 
-        if unsafe { syscall(SYS_futex, addr, FUTEX_WAIT | FUTEX_PRIVATE_FLAG, owning, 0) } < 0 {
-            let e = Error::last_os_error();
+        // Try to acquire the lock once.
+        let acquired = if self.uses_pi() {
+            unsafe { B::try_lock_pi(B::as_ptr(&self.owning)) }
+        } else {
+            B::compare_exchange_acquire(&self.owning, B::unlocked(), current).is_ok()
+        };
 
-            if e.raw_os_error().unwrap() != EAGAIN {
-                panic!("FUTEX_WAIT failed: {e}");
-            }
+        if !acquired {
+            return None;
         }
+
+        // SAFETY: This is safe because the current thread acquire the lock successfully above.
+        Some(unsafe { GroupGuard::new(self) })
     }
 
-    #[cfg(target_os = "macos")]
-    unsafe fn wait_unlock(addr: *mut u64, owning: u64) {
-        use ulock_sys::__ulock_wait;
-        use ulock_sys::darwin19::UL_COMPARE_AND_WAIT64;
+    /// Acquire the lock, giving up if it cannot be acquired within `timeout`.
+    ///
+    /// Returns [`None`] if the group is still owned by another thread when `timeout` elapses.
+    #[inline(never)]
+    pub(crate) fn lock_timeout(&self, timeout: Duration) -> Option<GroupGuard<'_, B>> {
+        // Check if the calling thread already own the lock.
+        let current = B::current_thread();
 
-        if __ulock_wait(UL_COMPARE_AND_WAIT64, addr.cast(), owning, 0) != 0 {
-            panic!("__ulock_wait() failed: {}", Error::last_os_error());
+        if current == B::owner_of(B::load(&self.owning)) {
+            // SAFETY: This is safe because the current thread own the lock.
+            return Some(unsafe { GroupGuard::new(self) });
         }
-    }
 
-    #[cfg(target_os = "windows")]
-    unsafe fn wait_unlock(addr: *mut u32, owning: u32) {
-        use windows_sys::Win32::System::Threading::{WaitOnAddress, INFINITE};
+        if self.uses_pi() {
+            if unsafe { B::lock_pi_timeout(B::as_ptr(&self.owning), timeout) } {
+                // SAFETY: This is safe because the current thread acquire the lock above.
+                return Some(unsafe { GroupGuard::new(self) });
+            }
 
-        if unsafe { WaitOnAddress(addr.cast(), &owning as *const u32 as _, 4, INFINITE) } == 0 {
-            panic!("WaitOnAddress() failed: {}", Error::last_os_error());
+            return None;
         }
-    }
 
-    #[cfg(target_os = "linux")]
-    unsafe fn wake_one(addr: *mut i32) {
-        use libc::{syscall, SYS_futex, FUTEX_PRIVATE_FLAG, FUTEX_WAKE};
+        let deadline = Deadline::new(timeout);
+        let mut timed_out = false;
+
+        // Acquire the lock.
+        while let Err(owning) = B::compare_exchange_acquire(&self.owning, B::unlocked(), current) {
+            if timed_out {
+                return None;
+            }
+
+            // Recompute the remaining time so spurious wakeups do not extend the deadline.
+            let remaining = deadline.remaining()?;
 
-        if unsafe { syscall(SYS_futex, addr, FUTEX_WAKE | FUTEX_PRIVATE_FLAG, 1) } < 0 {
-            panic!("FUTEX_WAKE failed: {}", Error::last_os_error());
+            // Wait for the lock to unlock. A timeout here loops back for one last acquire attempt
+            // before giving up.
+            timed_out = !unsafe { B::wait(B::as_ptr(&self.owning), owning, Some(remaining)) };
         }
+
+        // SAFETY: This is safe because the current thread acquire the lock successfully by the
+        // above compare_exchange().
+        Some(unsafe { GroupGuard::new(self) })
     }
+}
 
-    #[cfg(target_os = "macos")]
-    unsafe fn wake_one(addr: *mut u64) {
-        use libc::ENOENT;
-        use ulock_sys::__ulock_wake;
-        use ulock_sys::darwin19::UL_COMPARE_AND_WAIT64;
+unsafe impl<B: GutexBackend> Send for GutexGroup<B> {}
+unsafe impl<B: GutexBackend> Sync for GutexGroup<B> {}
 
-        if __ulock_wake(UL_COMPARE_AND_WAIT64, addr.cast(), 0) != 0 {
-            // __ulock_wake will return ENOENT if no other threads being waiting on the address.
-            let e = Error::last_os_error();
+/// Tracks a relative timeout as an absolute deadline.
+///
+/// Kept separate from the backend so `no_std` builds, which have no clock of their own here, still
+/// compile; the std backend is the only source of [`std::time::Instant`].
+#[cfg(feature = "std")]
+struct Deadline(Option<std::time::Instant>);
 
-            if e.raw_os_error().unwrap() != ENOENT {
-                panic!("__ulock_wake() failed: {e}");
-            }
+#[cfg(feature = "std")]
+impl Deadline {
+    fn new(timeout: Duration) -> Self {
+        Self(std::time::Instant::now().checked_add(timeout))
+    }
+
+    fn remaining(&self) -> Option<Duration> {
+        match self.0 {
+            Some(d) => match d.checked_duration_since(std::time::Instant::now()) {
+                Some(r) if !r.is_zero() => Some(r),
+                _ => None,
+            },
+            None => Some(Duration::MAX),
         }
     }
+}
 
-    #[cfg(target_os = "windows")]
-    unsafe fn wake_one(addr: *mut u32) {
-        use windows_sys::Win32::System::Threading::WakeByAddressSingle;
+/// Without a clock every wait is given the full timeout again, so a wakeup that loses the race for
+/// the lock restarts the timeout.
+#[cfg(not(feature = "std"))]
+struct Deadline(Duration);
 
-        unsafe { WakeByAddressSingle(addr.cast()) };
+#[cfg(not(feature = "std"))]
+impl Deadline {
+    fn new(timeout: Duration) -> Self {
+        Self(timeout)
     }
-}
 
-unsafe impl Send for GutexGroup {}
-unsafe impl Sync for GutexGroup {}
+    fn remaining(&self) -> Option<Duration> {
+        Some(self.0)
+    }
+}
 
 /// An RAII object used to release a lock on [`GutexGroup`]. This type cannot be send because it
 /// will cause data race on the group when dropping if more than one [`GroupGuard`] are active.
 #[derive(Debug)]
-pub(crate) struct GroupGuard<'a> {
-    group: &'a GutexGroup,
-    phantom: PhantomData<Rc<()>>, // For !Send and !Sync.
+pub(crate) struct GroupGuard<'a, B: GutexBackend = Futex> {
+    group: &'a GutexGroup<B>,
+    phantom: PhantomData<*const ()>, // For !Send and !Sync.
 }
 
-impl<'a> GroupGuard<'a> {
+impl<'a, B: GutexBackend> GroupGuard<'a, B> {
     /// # Safety
     /// The group must be locked by the calling thread with no active references to any of its
     /// field.
-    unsafe fn new(group: &'a GutexGroup) -> Self {
+    unsafe fn new(group: &'a GutexGroup<B>) -> Self {
         *group.active.get() += 1;
 
         Self {
@@ -162,9 +252,14 @@ impl<'a> GroupGuard<'a> {
             phantom: PhantomData,
         }
     }
+
+    /// Returns the group this guard is holding.
+    pub(crate) fn group(&self) -> &'a GutexGroup<B> {
+        self.group
+    }
 }
 
-impl<'a> Drop for GroupGuard<'a> {
+impl<'a, B: GutexBackend> Drop for GroupGuard<'a, B> {
     #[inline(never)]
     fn drop(&mut self) {
         // Decrease the active lock.
@@ -179,17 +274,14 @@ impl<'a> Drop for GroupGuard<'a> {
         }
 
         // Release the lock.
-        self.group.owning.store(0, Ordering::Release);
-
-        unsafe { GutexGroup::wake_one(self.group.owning.as_ptr()) };
+        if self.group.uses_pi() {
+            // FUTEX_UNLOCK_PI clears the owning word and hands it off to the highest-priority
+            // waiter atomically, so there is no separate wake.
+            unsafe { B::unlock_pi(B::as_ptr(&self.group.owning)) };
+        } else {
+            B::store_release(&self.group.owning, B::unlocked());
+
+            unsafe { B::wake_one(B::as_ptr(&self.group.owning)) };
+        }
     }
 }
-
-#[cfg(target_os = "linux")]
-type ThreadId = std::sync::atomic::AtomicI32;
-
-#[cfg(target_os = "macos")]
-type ThreadId = std::sync::atomic::AtomicU64;
-
-#[cfg(target_os = "windows")]
-type ThreadId = std::sync::atomic::AtomicU32;
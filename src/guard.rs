@@ -1,28 +1,29 @@
-use crate::{GroupGuard, Gutex};
-use std::fmt::{Display, Formatter};
-use std::ops::{Deref, DerefMut};
+use crate::backend::{Futex, GutexBackend};
+use crate::{GroupGuard, Gutex, GutexGroup};
+use core::fmt::{Display, Formatter};
+use core::ops::{Deref, DerefMut};
 
 /// RAII structure used to release the shared read access of a lock when dropped.
 #[derive(Debug)]
-pub struct GutexReadGuard<'a, T> {
+pub struct GutexReadGuard<'a, T, B: GutexBackend = Futex> {
     #[allow(dead_code)]
-    lock: GroupGuard<'a>,
-    mtx: &'a Gutex<T>,
+    lock: GroupGuard<'a, B>,
+    mtx: &'a Gutex<T, B>,
 }
 
-impl<'a, T> GutexReadGuard<'a, T> {
-    pub(crate) fn new(lock: GroupGuard<'a>, mtx: &'a Gutex<T>) -> Self {
+impl<'a, T, B: GutexBackend> GutexReadGuard<'a, T, B> {
+    pub(crate) fn new(lock: GroupGuard<'a, B>, mtx: &'a Gutex<T, B>) -> Self {
         Self { lock, mtx }
     }
 }
 
-impl<'a, T> Drop for GutexReadGuard<'a, T> {
+impl<'a, T, B: GutexBackend> Drop for GutexReadGuard<'a, T, B> {
     fn drop(&mut self) {
         unsafe { *self.mtx.active.get() -= 1 };
     }
 }
 
-impl<'a, T> Deref for GutexReadGuard<'a, T> {
+impl<'a, T, B: GutexBackend> Deref for GutexReadGuard<'a, T, B> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -30,42 +31,62 @@ impl<'a, T> Deref for GutexReadGuard<'a, T> {
     }
 }
 
-impl<'a, T: Display> Display for GutexReadGuard<'a, T> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+impl<'a, T: Display, B: GutexBackend> Display for GutexReadGuard<'a, T, B> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         self.deref().fmt(f)
     }
 }
 
-unsafe impl<'a, T: Sync> Sync for GutexReadGuard<'a, T> {}
+unsafe impl<'a, T: Sync, B: GutexBackend> Sync for GutexReadGuard<'a, T, B> {}
 
 /// RAII structure used to release the exclusive write access of a lock when dropped.
 #[derive(Debug)]
-pub struct GutexWriteGuard<'a, T> {
+pub struct GutexWriteGuard<'a, T, B: GutexBackend = Futex> {
     #[allow(dead_code)]
-    lock: GroupGuard<'a>,
+    lock: GroupGuard<'a, B>,
     active: *mut usize,
     value: *mut T,
 }
 
-impl<'a, T> GutexWriteGuard<'a, T> {
+impl<'a, T, B: GutexBackend> GutexWriteGuard<'a, T, B> {
     /// # Safety
     /// `active` and `value` must be protected by `lock`.
-    pub(crate) unsafe fn new(lock: GroupGuard<'a>, active: *mut usize, value: *mut T) -> Self {
+    pub(crate) unsafe fn new(lock: GroupGuard<'a, B>, active: *mut usize, value: *mut T) -> Self {
         Self {
             lock,
             active,
             value,
         }
     }
+
+    /// Returns the group this guard is holding the lock on.
+    pub(crate) fn group(&self) -> &'a GutexGroup<B> {
+        self.lock.group()
+    }
+
+    pub(crate) fn active_ptr(&self) -> *mut usize {
+        self.active
+    }
+
+    pub(crate) fn value_ptr(&self) -> *mut T {
+        self.value
+    }
 }
 
-impl<'a, T> Drop for GutexWriteGuard<'a, T> {
+impl<'a, T, B: GutexBackend> Drop for GutexWriteGuard<'a, T, B> {
     fn drop(&mut self) {
+        // Poison the group if we are unwinding out of a write access so the next acquirer is warned
+        // that the protected state may be inconsistent.
+        #[cfg(feature = "std")]
+        if std::thread::panicking() {
+            self.lock.group().poison();
+        }
+
         unsafe { *self.active = 0 };
     }
 }
 
-impl<'a, T> Deref for GutexWriteGuard<'a, T> {
+impl<'a, T, B: GutexBackend> Deref for GutexWriteGuard<'a, T, B> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -73,16 +94,16 @@ impl<'a, T> Deref for GutexWriteGuard<'a, T> {
     }
 }
 
-impl<'a, T> DerefMut for GutexWriteGuard<'a, T> {
+impl<'a, T, B: GutexBackend> DerefMut for GutexWriteGuard<'a, T, B> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         unsafe { &mut *self.value }
     }
 }
 
-impl<'a, T: Display> Display for GutexWriteGuard<'a, T> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+impl<'a, T: Display, B: GutexBackend> Display for GutexWriteGuard<'a, T, B> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         self.deref().fmt(f)
     }
 }
 
-unsafe impl<'a, T: Sync> Sync for GutexWriteGuard<'a, T> {}
+unsafe impl<'a, T: Sync, B: GutexBackend> Sync for GutexWriteGuard<'a, T, B> {}
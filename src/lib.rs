@@ -36,14 +36,31 @@
 //! The bonus point of [`Gutex`] is it will allow recursive lock for read-only access so you will
 //! never end up deadlock yourself. This read-only access is per [`Gutex`]. It will panic if you try
 //! to acquire write access while the readers are still active the same as [`std::cell::RefCell`].
-use std::cell::UnsafeCell;
-use std::sync::Arc;
+//!
+//! # `no_std`
+//!
+//! This crate is `#![no_std]`-compatible. Enable the default `std` feature for the built-in futex
+//! [`Futex`] backend or disable it and supply your own [`GutexBackend`] (for example mapping "wait
+//! on address"/"wake" to a custom kernel scheduler) via `GutexGroup::<MyBackend>::new_in()`.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use core::cell::UnsafeCell;
+use core::time::Duration;
 
+pub use self::backend::*;
+pub use self::condvar::*;
 pub use self::group::*;
 pub use self::guard::*;
+pub use self::poison::*;
 
+mod backend;
+mod condvar;
 mod group;
 mod guard;
+mod poison;
 
 /// Member of a [`GutexGroup`].
 ///
@@ -52,25 +69,41 @@ mod guard;
 /// try to call this method on the same group. The result is thread B will wait for thread A to
 /// unlock the group.
 #[derive(Debug)]
-pub struct Gutex<T> {
-    group: Arc<GutexGroup>,
+pub struct Gutex<T, B: GutexBackend = Futex> {
+    group: Arc<GutexGroup<B>>,
     active: UnsafeCell<usize>,
     value: UnsafeCell<T>,
 }
 
-impl<T> Gutex<T> {
+impl<T, B: GutexBackend> Gutex<T, B> {
     /// Returns a mutable reference to the underlying data.
+    ///
+    /// This takes `&mut self` so no locking is required and the poison state is irrelevant.
     pub fn get_mut(&mut self) -> &mut T {
         self.value.get_mut()
     }
 
+    /// Returns `true` if a thread panicked while holding a write access on any member of the group.
+    pub fn is_poisoned(&self) -> bool {
+        self.group.is_poisoned()
+    }
+
+    /// Clear the poisoned state of this group so subsequent locks succeed again.
+    pub fn clear_poison(&self) {
+        self.group.clear_poison();
+    }
+
     /// Locks this [`Gutex`] with read-only access.
     ///
     /// Multiple read-only accesses can be taken out at the same time.
     ///
+    /// # Errors
+    /// If a thread panicked while holding a write access on any member of the group, the returned
+    /// guard is wrapped in an [`Err`] so the possibly-inconsistent state is not used by accident.
+    ///
     /// # Panics
     /// If there are an active write access to this [`Gutex`].
-    pub fn read(&self) -> GutexReadGuard<T> {
+    pub fn read(&self) -> LockResult<GutexReadGuard<T, B>> {
         // Check if there are an active writer.
         let lock = self.group.lock();
         let active = self.active.get();
@@ -86,20 +119,100 @@ impl<T> Gutex<T> {
             *active += 1;
         }
 
-        GutexReadGuard::new(lock, self)
+        let guard = GutexReadGuard::new(lock, self);
+
+        if self.group.is_poisoned() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    /// Attempts to acquire read-only access to this [`Gutex`] without blocking.
+    ///
+    /// # Errors
+    /// Returns [`TryLockError::WouldBlock`] if the group is currently owned by another thread or if
+    /// there are an active write access to this [`Gutex`], and [`TryLockError::Poisoned`] if a
+    /// thread panicked while holding a write access on any member of the group.
+    pub fn try_read(&self) -> TryLockResult<GutexReadGuard<T, B>> {
+        let lock = match self.group.try_lock() {
+            Some(lock) => lock,
+            None => return Err(TryLockError::WouldBlock),
+        };
+        let active = self.active.get();
+
+        // SAFETY: This is safe because we own the lock that protect both active and value.
+        unsafe {
+            if *active == usize::MAX {
+                return Err(TryLockError::WouldBlock);
+            } else if *active == (usize::MAX - 1) {
+                // This should never happen because stack overflow should be triggering first.
+                panic!("maximum number of active readers has been reached");
+            }
+
+            *active += 1;
+        }
+
+        let guard = GutexReadGuard::new(lock, self);
+
+        if self.group.is_poisoned() {
+            Err(TryLockError::Poisoned(PoisonError::new(guard)))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    /// Locks this [`Gutex`] with read-only access, giving up if the group cannot be acquired within
+    /// `timeout`.
+    ///
+    /// Returns [`TryLockError::WouldBlock`] if `timeout` elapses before the group is acquired, and
+    /// [`TryLockError::Poisoned`] if a thread panicked while holding a write access on any member
+    /// of the group.
+    ///
+    /// # Panics
+    /// If there are an active write access to this [`Gutex`].
+    pub fn read_timeout(&self, timeout: Duration) -> TryLockResult<GutexReadGuard<T, B>> {
+        let lock = match self.group.lock_timeout(timeout) {
+            Some(lock) => lock,
+            None => return Err(TryLockError::WouldBlock),
+        };
+        let active = self.active.get();
+
+        unsafe {
+            if *active == usize::MAX {
+                panic!("attempt to acquire the read lock while there are an active write lock");
+            } else if *active == (usize::MAX - 1) {
+                // This should never happen because stack overflow should be triggering first.
+                panic!("maximum number of active readers has been reached");
+            }
+
+            *active += 1;
+        }
+
+        let guard = GutexReadGuard::new(lock, self);
+
+        if self.group.is_poisoned() {
+            Err(TryLockError::Poisoned(PoisonError::new(guard)))
+        } else {
+            Ok(guard)
+        }
     }
 
     /// Locks this [`Gutex`] with write access.
     ///
+    /// # Errors
+    /// If a thread panicked while holding a write access on any member of the group, the returned
+    /// guard is wrapped in an [`Err`] so the possibly-inconsistent state is not used by accident.
+    ///
     /// # Panics
     /// If there are any active reader or writer.
-    pub fn write(&self) -> GutexWriteGuard<T> {
+    pub fn write(&self) -> LockResult<GutexWriteGuard<T, B>> {
         // Check if there are active reader or writer.
         let lock = self.group.lock();
         let active = self.active.get();
 
         // SAFETY: This is safe because we own the lock that protect both active and value.
-        unsafe {
+        let guard = unsafe {
             if *active != 0 {
                 panic!(
                     "attempt to acquire the write lock while there are an active reader or writer"
@@ -109,12 +222,85 @@ impl<T> Gutex<T> {
             *active = usize::MAX;
 
             GutexWriteGuard::new(lock, active, self.value.get())
+        };
+
+        if self.group.is_poisoned() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    /// Attempts to acquire write access to this [`Gutex`] without blocking.
+    ///
+    /// # Errors
+    /// Returns [`TryLockError::WouldBlock`] if the group is currently owned by another thread or if
+    /// there are any active reader or writer, and [`TryLockError::Poisoned`] if a thread panicked
+    /// while holding a write access on any member of the group.
+    pub fn try_write(&self) -> TryLockResult<GutexWriteGuard<T, B>> {
+        let lock = match self.group.try_lock() {
+            Some(lock) => lock,
+            None => return Err(TryLockError::WouldBlock),
+        };
+        let active = self.active.get();
+
+        // SAFETY: This is safe because we own the lock that protect both active and value.
+        let guard = unsafe {
+            if *active != 0 {
+                return Err(TryLockError::WouldBlock);
+            }
+
+            *active = usize::MAX;
+
+            GutexWriteGuard::new(lock, active, self.value.get())
+        };
+
+        if self.group.is_poisoned() {
+            Err(TryLockError::Poisoned(PoisonError::new(guard)))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    /// Locks this [`Gutex`] with write access, giving up if the group cannot be acquired within
+    /// `timeout`.
+    ///
+    /// Returns [`TryLockError::WouldBlock`] if `timeout` elapses before the group is acquired, and
+    /// [`TryLockError::Poisoned`] if a thread panicked while holding a write access on any member
+    /// of the group.
+    ///
+    /// # Panics
+    /// If there are any active reader or writer.
+    pub fn write_timeout(&self, timeout: Duration) -> TryLockResult<GutexWriteGuard<T, B>> {
+        let lock = match self.group.lock_timeout(timeout) {
+            Some(lock) => lock,
+            None => return Err(TryLockError::WouldBlock),
+        };
+        let active = self.active.get();
+
+        // SAFETY: This is safe because we own the lock that protect both active and value.
+        let guard = unsafe {
+            if *active != 0 {
+                panic!(
+                    "attempt to acquire the write lock while there are an active reader or writer"
+                );
+            }
+
+            *active = usize::MAX;
+
+            GutexWriteGuard::new(lock, active, self.value.get())
+        };
+
+        if self.group.is_poisoned() {
+            Err(TryLockError::Poisoned(PoisonError::new(guard)))
+        } else {
+            Ok(guard)
         }
     }
 }
 
-unsafe impl<T: Send> Send for Gutex<T> {}
-unsafe impl<T: Send> Sync for Gutex<T> {}
+unsafe impl<T: Send, B: GutexBackend> Send for Gutex<T, B> {}
+unsafe impl<T: Send, B: GutexBackend> Sync for Gutex<T, B> {}
 
 #[cfg(test)]
 mod tests {
@@ -126,14 +312,14 @@ mod tests {
     fn group_lock() {
         let b = Arc::new(Barrier::new(2));
         let v = Arc::new(GutexGroup::new().spawn(0));
-        let mut l = v.write();
+        let mut l = v.write().unwrap();
         let t = std::thread::spawn({
             let b = b.clone();
             let v = v.clone();
 
             move || {
                 // Wait for parent thread.
-                let mut l = v.write();
+                let mut l = v.write().unwrap();
 
                 b.wait();
 
@@ -153,8 +339,48 @@ mod tests {
         // Wait for the inner thread value.
         b.wait();
 
-        assert_eq!(*v.read(), 2);
+        assert_eq!(*v.read().unwrap(), 2);
 
         t.join().unwrap();
     }
+
+    #[test]
+    fn try_write_would_block() {
+        let v = Arc::new(GutexGroup::new().spawn(0));
+        let _l = v.write().unwrap();
+
+        // The group is reentrant but the member already has an active writer.
+        assert!(matches!(v.try_write(), Err(TryLockError::WouldBlock)));
+        assert!(matches!(v.try_read(), Err(TryLockError::WouldBlock)));
+    }
+
+    #[test]
+    fn poison_on_panic() {
+        let v = Arc::new(GutexGroup::new().spawn(0));
+        let p = v.clone();
+
+        // A writer that panics must poison the group.
+        std::thread::spawn(move || {
+            let _l = p.write().unwrap();
+
+            panic!("boom");
+        })
+        .join()
+        .unwrap_err();
+
+        assert!(v.is_poisoned());
+        assert!(v.write().is_err());
+
+        // The carried guard still grants access for deliberate recovery.
+        let mut l = v.write().unwrap_err().into_inner();
+
+        *l = 1;
+        drop(l);
+
+        // Clearing the flag makes subsequent locks succeed again.
+        v.clear_poison();
+
+        assert!(!v.is_poisoned());
+        assert_eq!(*v.read().unwrap(), 1);
+    }
 }
@@ -0,0 +1,96 @@
+use core::fmt::{Debug, Display, Formatter};
+
+/// Alias for a [`Result`] that carries a guard even on failure.
+///
+/// A group in which a thread panicked while holding write access to any member is considered
+/// *poisoned*; locking any of its members then yields [`Err`] so the corrupted state cannot be used
+/// unknowingly. This mirrors
+/// [`std::sync::LockResult`].
+pub type LockResult<G> = Result<G, PoisonError<G>>;
+
+/// Alias for a [`Result`] returned by the non-blocking and timed lock methods.
+///
+/// Unlike [`LockResult`] the failure can be either a poisoned group or an inability to acquire the
+/// group without blocking past the allowed time. This mirrors [`std::sync::TryLockResult`].
+pub type TryLockResult<G> = Result<G, TryLockError<G>>;
+
+/// Failure returned from [`Gutex::try_read`], [`Gutex::try_write`] and their timed counterparts.
+///
+/// This mirrors [`std::sync::TryLockError`].
+///
+/// [`Gutex::try_read`]: crate::Gutex::try_read
+/// [`Gutex::try_write`]: crate::Gutex::try_write
+pub enum TryLockError<G> {
+    /// The group was poisoned by a panicking writer. The guard is still carried so the data can be
+    /// recovered deliberately.
+    Poisoned(PoisonError<G>),
+
+    /// The group could not be acquired within the allowed time without blocking.
+    WouldBlock,
+}
+
+impl<G> Debug for TryLockError<G> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Poisoned(e) => Debug::fmt(e, f),
+            Self::WouldBlock => f.write_str("WouldBlock"),
+        }
+    }
+}
+
+impl<G> Display for TryLockError<G> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Poisoned(..) => f.write_str("poisoned lock: another task failed inside the group"),
+            Self::WouldBlock => f.write_str("try_lock failed because the group is held"),
+        }
+    }
+}
+
+impl<G> From<PoisonError<G>> for TryLockError<G> {
+    fn from(e: PoisonError<G>) -> Self {
+        Self::Poisoned(e)
+    }
+}
+
+/// Returned from a lock whose group was poisoned by a panicking writer.
+///
+/// The failed guard is still carried so a caller that understands the situation can recover the
+/// data deliberately via [`PoisonError::into_inner`]. This mirrors [`std::sync::PoisonError`].
+pub struct PoisonError<G> {
+    guard: G,
+}
+
+impl<G> PoisonError<G> {
+    /// Wrap a guard taken from a poisoned group.
+    pub fn new(guard: G) -> Self {
+        Self { guard }
+    }
+
+    /// Consume the error, returning the underlying guard.
+    pub fn into_inner(self) -> G {
+        self.guard
+    }
+
+    /// Reference to the underlying guard.
+    pub fn get_ref(&self) -> &G {
+        &self.guard
+    }
+
+    /// Mutable reference to the underlying guard.
+    pub fn get_mut(&mut self) -> &mut G {
+        &mut self.guard
+    }
+}
+
+impl<G> Debug for PoisonError<G> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PoisonError").finish_non_exhaustive()
+    }
+}
+
+impl<G> Display for PoisonError<G> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_str("poisoned lock: another task failed inside the group")
+    }
+}